@@ -1,31 +1,127 @@
 // External imports
-use std::any::Any;
+use thiserror::Error;
 
 // Internal imports
 use crate::lexer::Token;
-use crate::parser::{Expr, Literal};
-use crate::vm::{Chunk, OpCode};
+use crate::parser::{Expr, Literal, Stmt};
+use crate::vm::{Byte, Chunk, OpCode, Register};
+
+/// An error that can be returned from the compiler.
+#[derive(Debug, Error)]
+pub enum CompileError {
+    #[error("ran out of free registers while compiling a deeply nested expression")]
+    RegisterStackUnderflow,
+    #[error("statement {0:?} not yet supported by the compiler")]
+    UnsupportedStatement(Stmt),
+    #[error("expression {0:?} not yet supported by the compiler")]
+    UnsupportedExpression(Expr),
+}
 
 /// Interpreter that takes an AST and executes stuff based on it.
 struct Compiler {
     /// The ast our compiler parses into bytecode.
-    ast: Vec<Box<Expr>>,
+    ast: Vec<Stmt>,
+    /// Pool of numeric constants referenced by `OpCode::LoadConst`.
+    constants: Vec<f64>,
+    /// Registers not currently holding a live value.
+    /// `R0` sits on top so it is allocated first.
+    free_registers: Vec<Register>,
 }
 
 impl Compiler {
     /// Returns a new compiler using the given ast.
-    pub fn new(ast: Vec<Box<Expr>>) -> Self {
-        Self { ast }
+    pub fn new(ast: Vec<Stmt>) -> Self {
+        Self {
+            ast,
+            constants: Vec::new(),
+            free_registers: vec![
+                Register::R7,
+                Register::R6,
+                Register::R5,
+                Register::R4,
+                Register::R3,
+                Register::R2,
+                Register::R1,
+                Register::R0,
+            ],
+        }
     }
 
     /// Parses an ast into a `Vec` of VM chunks.
-    pub fn parse(&mut self) -> Vec<Chunk> {
-        todo!()
+    pub fn parse(&mut self) -> Result<Vec<Chunk>, CompileError> {
+        // Create vec to hold emitted chunks
+        let mut chunks = Vec::new();
+
+        // Evaluate each top-level expression statement in order, collecting its chunks
+        let ast = std::mem::take(&mut self.ast);
+        for stmt in &ast {
+            match stmt {
+                Stmt::Expr(expr) => {
+                    // The result isn't needed past the end of its own statement;
+                    // free it so later statements can reuse the register, and so
+                    // whichever one runs last is the one left in R0 for the VM to return.
+                    let result = self.evaluate(expr, &mut chunks)?;
+                    self.free_registers.push(result);
+                }
+                stmt => return Err(CompileError::UnsupportedStatement(stmt.clone()))
+            }
+        }
+
+        Ok(chunks)
+    }
+
+    /// Evaluates an expression, appending its bytecode to `chunks`,
+    /// and returns the register holding its result.
+    fn evaluate(&mut self, expr: &Box<Expr>, chunks: &mut Vec<Chunk>) -> Result<Register, CompileError> {
+        match expr.as_ref() {
+            Expr::Literal(Literal::Number(n)) => {
+                let idx = self.push_constant(*n);
+                let dst = self.alloc_register()?;
+                chunks.push(Chunk::new([OpCode::LoadConst.into(), dst.into(), idx]));
+                Ok(dst)
+            }
+            Expr::Unary { op: Token::Minus, right, .. } => {
+                // Load `0.0` into a fresh register, then subtract `right` from it
+                let idx = self.push_constant(0.0);
+                let dst = self.alloc_register()?;
+                chunks.push(Chunk::new([OpCode::LoadConst.into(), dst.into(), idx]));
+
+                let rr = self.evaluate(right, chunks)?;
+                chunks.push(Chunk::new([OpCode::Sub.into(), dst.into(), rr.into()]));
+                self.free_registers.push(rr);
+
+                Ok(dst)
+            }
+            Expr::Binary { left, op, right, .. } => {
+                let rl = self.evaluate(left, chunks)?;
+                let rr = self.evaluate(right, chunks)?;
+
+                let opcode = match op {
+                    Token::Plus => OpCode::Add,
+                    Token::Minus => OpCode::Sub,
+                    _ => return Err(CompileError::UnsupportedExpression((**expr).clone())),
+                };
+
+                // Writes its result into `rl`, freeing `rr`
+                chunks.push(Chunk::new([opcode.into(), rl.into(), rr.into()]));
+                self.free_registers.push(rr);
+
+                Ok(rl)
+            }
+            Expr::Grouping(inner) => self.evaluate(inner, chunks),
+            expr => Err(CompileError::UnsupportedExpression(expr.clone())),
+        }
     }
 
-    /// Evaluates an expression and parses it to a chunk of bytecode.
-    fn evaluate(&mut self, expr: &Box<Expr>) -> Chunk {
-        todo!()
+    /// Pushes a constant into the constant pool, returning its index.
+    fn push_constant(&mut self, value: f64) -> Byte {
+        self.constants.push(value);
+        (self.constants.len() - 1) as Byte
+    }
+
+    /// Pops a free register off the register stack.
+    fn alloc_register(&mut self) -> Result<Register, CompileError> {
+        self.free_registers.pop().ok_or(CompileError::RegisterStackUnderflow)
     }
 }
 
@@ -39,18 +135,97 @@ mod tests {
     #[test]
     fn test_compiler_evaluate() {
         // Create ast
-        let ast = parser::produce_ast("-1 + -2");
+        let ast = parser::produce_ast("-1 + -2").unwrap();
         // Print ast
         parser::print_ast(&ast);
 
         // Create compiler
         let mut compiler = Compiler::new(ast);
         // Get bytecode representation of ast
-        // let bytecode = compiler.parse();
+        let bytecode = compiler.parse().unwrap();
 
         // Print dissasembled bytecode for each chunk
-        // for chunk in bytecode.iter() {
-            // Do dissasembling and printing here!
-        // }
+        for chunk in bytecode.iter() {
+            println!("{}", chunk.dissasemble());
+        }
+    }
+
+    /// Tests that a statement the compiler can't yet emit bytecode for is
+    /// reported as a `CompileError`, rather than panicking via `todo!()`.
+    #[test]
+    fn test_compiler_unsupported_statement_is_an_error() {
+        let ast = parser::produce_ast("let x = 5;").unwrap();
+        let mut compiler = Compiler::new(ast);
+
+        assert!(matches!(compiler.parse(), Err(CompileError::UnsupportedStatement(_))));
+    }
+
+    /// Tests that an expression the compiler can't yet emit bytecode for (e.g.
+    /// a comparison operator) is reported as a `CompileError`, rather than
+    /// panicking via `todo!()`.
+    #[test]
+    fn test_compiler_unsupported_expression_is_an_error() {
+        let ast = parser::produce_ast("1 == 2;").unwrap();
+        let mut compiler = Compiler::new(ast);
+
+        assert!(matches!(compiler.parse(), Err(CompileError::UnsupportedExpression(_))));
+    }
+
+    /// Tests that a compiled program runs through the VM and matches
+    /// what the tree-walking interpreter would compute for the same code.
+    #[test]
+    fn test_compiler_vm_roundtrip() {
+        use crate::interpreter::RuntimeVal;
+        use crate::vm::VM;
+
+        // Create ast
+        let ast = parser::produce_ast("1 + 2").unwrap();
+
+        // Compile ast into chunks
+        let mut compiler = Compiler::new(ast);
+        let chunks = compiler.parse().unwrap();
+
+        // Run the compiled chunks through the VM
+        let mut vm = VM::new(chunks, compiler.constants.clone());
+        let result = vm.run().unwrap();
+
+        if let RuntimeVal::Number(n) = result {
+            assert_eq!(n, 3.0);
+        } else {
+            panic!("Expected a Number runtime value. Got '{:?}'!", result);
+        }
+    }
+
+    /// Tests that a program's overall value is its last statement's value,
+    /// not its first (each statement's result register must be freed so it
+    /// doesn't permanently squat on R0).
+    #[test]
+    fn test_compiler_vm_multiple_statements_yield_last_value() {
+        use crate::interpreter::RuntimeVal;
+        use crate::vm::VM;
+
+        let ast = parser::produce_ast("1 + 2; 3 + 4").unwrap();
+
+        let mut compiler = Compiler::new(ast);
+        let chunks = compiler.parse().unwrap();
+
+        let mut vm = VM::new(chunks, compiler.constants.clone());
+        let result = vm.run().unwrap();
+
+        if let RuntimeVal::Number(n) = result {
+            assert_eq!(n, 7.0);
+        } else {
+            panic!("Expected a Number runtime value. Got '{:?}'!", result);
+        }
+    }
+
+    /// Tests that compiling many trivial top-level statements in a row
+    /// doesn't leak registers and run into `RegisterStackUnderflow`.
+    #[test]
+    fn test_compiler_does_not_leak_registers_across_statements() {
+        let ast = parser::produce_ast("1+2; 1+2; 1+2; 1+2; 1+2; 1+2; 1+2; 1+2; 1+2;").unwrap();
+        let mut compiler = Compiler::new(ast);
+
+        assert!(compiler.parse().is_ok());
     }
 }
@@ -1,18 +1,19 @@
 // External imports
+use std::collections::HashMap;
 
 // Internal imports
-use crate::lexer::Token;
-use crate::parser::{Expr, Literal};
+use crate::lexer::{Position, Token};
+use crate::parser::{Expr, Literal, ParseError, Stmt};
 
 /// A runtime bool type.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum RuntimeBool {
     True,
     False
 }
 
 /// A runtime value.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum RuntimeVal {
     String(String),
     Number(f64),
@@ -20,49 +21,148 @@ pub enum RuntimeVal {
     Nil
 }
 
+impl RuntimeVal {
+    /// Returns whether this value is truthy. Only `nil` and `false` are falsey.
+    fn is_truthy(&self) -> bool {
+        !matches!(self, RuntimeVal::Nil | RuntimeVal::Bool(RuntimeBool::False))
+    }
+}
+
 /// An error that can be returned from the interpreter.
 #[derive(Debug, thiserror::Error)]
 pub enum InterpError {
     #[error("literal is of type 'Expr'")]
     LiteralIsExpr,
-    #[error("Unary operator of type {0:?} not valid on {1:?}")]
-    InvalidUnaryOperator(Token, RuntimeVal),
-    #[error("Unary operator of type {0:?} not supported on non-numbers")]
-    UnaryOperatorOnNonNumber(Token),
-    #[error("Binary operator of type {0:?} not valid between {1:?} and {2:?}")]
-    InvalidBinaryOperator(Token, RuntimeVal, RuntimeVal),
-    #[error("Binary operator of type {0:?} not valid between non numbers {1:?} and {2:?}")]
-    BinaryOperatorOnNonNumber(Token, RuntimeVal, RuntimeVal)
+    #[error("at {2}: Unary operator of type {0:?} not valid on {1:?}")]
+    InvalidUnaryOperator(Token, RuntimeVal, Position),
+    #[error("at {1}: Unary operator of type {0:?} not supported on non-numbers")]
+    UnaryOperatorOnNonNumber(Token, Position),
+    #[error("at {3}: Binary operator of type {0:?} not valid between {1:?} and {2:?}")]
+    InvalidBinaryOperator(Token, RuntimeVal, RuntimeVal, Position),
+    #[error("at {3}: Binary operator of type {0:?} not valid between non numbers {1:?} and {2:?}")]
+    BinaryOperatorOnNonNumber(Token, RuntimeVal, RuntimeVal, Position),
+    #[error("at {0}: attempted to divide by zero")]
+    DivideByZero(Position),
+    #[error("at {1}: undefined variable '{0}'")]
+    UndefinedVariable(String, Position),
+    #[error("syntax error(s): {0:?}")]
+    ParseErrors(Vec<ParseError>)
+}
+
+/// A stack of lexical scopes mapping variable names to their runtime values.
+/// The last scope in the stack is the innermost one.
+struct Environment {
+    scopes: Vec<HashMap<String, RuntimeVal>>
+}
+
+impl Environment {
+    /// Returns a new environment with a single, empty top-level scope.
+    fn new() -> Self {
+        Self { scopes: vec![HashMap::new()] }
+    }
+
+    /// Pushes a new, empty scope onto the stack.
+    fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    /// Pops the innermost scope off the stack.
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Defines `name` in the innermost scope, shadowing any outer binding of the same name.
+    fn define(&mut self, name: String, value: RuntimeVal) {
+        self.scopes.last_mut().expect("environment always has at least one scope").insert(name, value);
+    }
+
+    /// Looks up `name`, searching from the innermost scope outward.
+    fn get(&self, name: &str) -> Option<&RuntimeVal> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Updates an existing binding of `name`, searching from the innermost scope
+    /// outward, and writing into whichever scope already has it. Unlike `define`,
+    /// this never creates a new binding; it returns `false` if `name` isn't bound
+    /// anywhere in the stack.
+    fn assign(&mut self, name: &str, value: RuntimeVal) -> bool {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(slot) = scope.get_mut(name) {
+                *slot = value;
+                return true;
+            }
+        }
+
+        false
+    }
 }
 
 /// Interpreter that takes an AST and executes stuff based on it.
 pub struct Interpreter {
-    /// The ast our compiler parses into bytecode.
-    ast: Vec<Box<Expr>>,
+    /// The ast our parser parses into statements.
+    ast: Vec<Stmt>,
+    /// The variable scopes the interpreter executes against.
+    environment: Environment,
 }
 
 impl Interpreter {
     /// Returns a new compiler using the given ast.
-    pub fn new(ast: Vec<Box<Expr>>) -> Self {
-        Self { ast }
+    pub fn new(ast: Vec<Stmt>) -> Self {
+        Self { ast, environment: Environment::new() }
     }
 
-    /// Parses the ast.  
+    /// Parses the ast.
     /// The return value should only be used for debugging or error-checking.
-    pub fn parse(self) -> Vec<Result<RuntimeVal, InterpError>> {
+    pub fn parse(mut self) -> Vec<Result<RuntimeVal, InterpError>> {
         // Create vec to store runtime vals
         let mut runtime_vals = Vec::new();
 
-        for expr in self.ast {
-            runtime_vals.push(Self::evaluate(expr))
+        let ast = std::mem::take(&mut self.ast);
+        for stmt in ast {
+            runtime_vals.push(self.execute(stmt))
         }
 
         // Return runtime vals with no errors
         runtime_vals
     }
 
+    /// Executes a single statement, returning the runtime value it produces.
+    fn execute(&mut self, stmt: Stmt) -> Result<RuntimeVal, InterpError> {
+        match stmt {
+            Stmt::Expr(expr) => Self::evaluate(&mut self.environment, expr),
+            Stmt::Let { name, initializer } => {
+                let value = match initializer {
+                    Some(expr) => Self::evaluate(&mut self.environment, expr)?,
+                    None => RuntimeVal::Nil
+                };
+                self.environment.define(name, value);
+                Ok(RuntimeVal::Nil)
+            }
+            Stmt::While { condition, body } => {
+                while Self::evaluate(&mut self.environment, condition.clone())?.is_truthy() {
+                    self.execute((*body).clone())?;
+                }
+                Ok(RuntimeVal::Nil)
+            }
+            Stmt::Block(stmts) => {
+                self.environment.push_scope();
+
+                let mut result = Ok(RuntimeVal::Nil);
+                for stmt in stmts {
+                    result = self.execute(stmt);
+                    if result.is_err() {
+                        break;
+                    }
+                }
+
+                self.environment.pop_scope();
+                result
+            }
+        }
+    }
+
     /// Evaluates an Expr ast node and returns a runtime value.
-    fn evaluate(expr: Box<Expr>) -> Result<RuntimeVal, InterpError> {
+    fn evaluate(env: &mut Environment, expr: Box<Expr>) -> Result<RuntimeVal, InterpError> {
         match *expr {
             // PARSING FOR MATH EXPRESSIONS
             Expr::Literal(l) => {
@@ -75,9 +175,12 @@ impl Interpreter {
                     Literal::False => Ok(RuntimeVal::Bool(RuntimeBool::False))
                 }
             },
-            Expr::Unary {op, ref right} => {
+            Expr::Identifier(name, position) => {
+                env.get(&name).cloned().ok_or(InterpError::UndefinedVariable(name, position))
+            }
+            Expr::Unary {op, ref right, position} => {
                 // Evaluate right expression
-                let right_eval = Self::evaluate(right.clone())?;
+                let right_eval = Self::evaluate(env, right.clone())?;
 
                 match op {
                     Token::Minus => {
@@ -85,53 +188,82 @@ impl Interpreter {
                         if let RuntimeVal::Number(n) = right_eval {
                             Ok(RuntimeVal::Number(-n))
                         } else {
-                            Err(InterpError::UnaryOperatorOnNonNumber(op))
+                            Err(InterpError::UnaryOperatorOnNonNumber(op, position))
                         }
                     },
+                    Token::Bang => {
+                        // Flip truthiness: Nil and False are falsey, everything else truthy
+                        Ok(RuntimeVal::Bool(if right_eval.is_truthy() { RuntimeBool::False } else { RuntimeBool::True }))
+                    }
                     _ => {
-                        Err(InterpError::InvalidUnaryOperator(op, right_eval))
+                        Err(InterpError::InvalidUnaryOperator(op, right_eval, position))
                     }
                 }
             }
-            Expr::Binary {left, op, right} => {
+            Expr::Binary {left, op, right, position} => {
                 // Get evaluated right and left expressions
-                let left_eval = Self::evaluate(left)?;
-                let right_eval = Self::evaluate(right)?;
+                let left_eval = Self::evaluate(env, left)?;
+                let right_eval = Self::evaluate(env, right)?;
 
                 // Return expression based on operator
                 match op {
                     Token::Plus => {
-                        // Try to get number of both values
-                        if let RuntimeVal::Number(left_num) = left_eval {
-                            if let RuntimeVal::Number(right_num) = right_eval {
-                                Ok(RuntimeVal::Number(left_num + right_num))
-                            } else {
-                                Err(InterpError::BinaryOperatorOnNonNumber(op, left_eval, right_eval))
-                            }
+                        // Concatenate if both sides are strings, otherwise add as numbers
+                        if let (RuntimeVal::String(l), RuntimeVal::String(r)) = (&left_eval, &right_eval) {
+                            Ok(RuntimeVal::String(format!("{}{}", l, r)))
                         } else {
-                            Err(InterpError::BinaryOperatorOnNonNumber(op, left_eval, right_eval))
+                            Self::numeric_binary(op, left_eval, right_eval, position, |l, r| RuntimeVal::Number(l + r))
                         }
                     }
-                    Token::Minus => {
-                        // Try to get number of both values
-                        if let RuntimeVal::Number(left_num) = left_eval {
-                            if let RuntimeVal::Number(right_num) = right_eval {
-                                Ok(RuntimeVal::Number(left_num - right_num))
-                            } else {
-                                Err(InterpError::BinaryOperatorOnNonNumber(op, left_eval, right_eval))
+                    Token::Minus => Self::numeric_binary(op, left_eval, right_eval, position, |l, r| RuntimeVal::Number(l - r)),
+                    Token::Mult => Self::numeric_binary(op, left_eval, right_eval, position, |l, r| RuntimeVal::Number(l * r)),
+                    Token::Div => {
+                        if let RuntimeVal::Number(r) = &right_eval {
+                            if *r == 0.0 {
+                                return Err(InterpError::DivideByZero(position));
                             }
-                        } else {
-                            Err(InterpError::BinaryOperatorOnNonNumber(op, left_eval, right_eval))
                         }
+
+                        Self::numeric_binary(op, left_eval, right_eval, position, |l, r| RuntimeVal::Number(l / r))
                     }
-                    tk => Err(InterpError::InvalidBinaryOperator(tk, left_eval, right_eval))
+                    Token::Less => Self::numeric_binary(op, left_eval, right_eval, position, |l, r| RuntimeVal::Bool(Self::bool_from(l < r))),
+                    Token::Greater => Self::numeric_binary(op, left_eval, right_eval, position, |l, r| RuntimeVal::Bool(Self::bool_from(l > r))),
+                    Token::LEqual => Self::numeric_binary(op, left_eval, right_eval, position, |l, r| RuntimeVal::Bool(Self::bool_from(l <= r))),
+                    Token::GEqual => Self::numeric_binary(op, left_eval, right_eval, position, |l, r| RuntimeVal::Bool(Self::bool_from(l >= r))),
+                    Token::DEqual => Ok(RuntimeVal::Bool(Self::bool_from(left_eval == right_eval))),
+                    Token::NEqual => Ok(RuntimeVal::Bool(Self::bool_from(left_eval != right_eval))),
+                    tk => Err(InterpError::InvalidBinaryOperator(tk, left_eval, right_eval, position))
                 }
             }
             Expr::Grouping(g) => {
-                Ok(Self::evaluate(g)?)
+                Ok(Self::evaluate(env, g)?)
+            }
+            Expr::Assign { name, value, position } => {
+                let value = Self::evaluate(env, value)?;
+                if env.assign(&name, value.clone()) {
+                    Ok(value)
+                } else {
+                    Err(InterpError::UndefinedVariable(name, position))
+                }
             }
         }
     }
+
+    /// Applies `f` to both operands if they are both numbers, erroring otherwise.
+    fn numeric_binary<F>(op: Token, left: RuntimeVal, right: RuntimeVal, position: Position, f: F) -> Result<RuntimeVal, InterpError>
+    where
+        F: FnOnce(f64, f64) -> RuntimeVal
+    {
+        match (&left, &right) {
+            (RuntimeVal::Number(l), RuntimeVal::Number(r)) => Ok(f(*l, *r)),
+            _ => Err(InterpError::BinaryOperatorOnNonNumber(op, left, right, position))
+        }
+    }
+
+    /// Converts a plain bool into a `RuntimeBool`.
+    fn bool_from(b: bool) -> RuntimeBool {
+        if b { RuntimeBool::True } else { RuntimeBool::False }
+    }
 }
 
 /// Takes a piece of code and lexes it, parses it, and interpretes it, displaying its result.
@@ -140,7 +272,7 @@ pub fn interp_code(code: &'static str) -> Result<(), InterpError> {
     use super::parser;
 
     // Produce ast
-    let ast = parser::produce_ast(code);
+    let ast = parser::produce_ast(code).map_err(InterpError::ParseErrors)?;
 
     // Create interpreter
     let interp = Interpreter::new(ast);
@@ -175,4 +307,121 @@ mod tests {
         println!("\n-------NEGATIVE ADD RESULT-------");
         interp_code("1 - 2").unwrap();
     }
+
+    /// Test result of multiplying and dividing numbers.
+    #[test]
+    fn test_interp_mult_div() {
+        interp_code("2 * 3").unwrap();
+        interp_code("6 / 2").unwrap();
+    }
+
+    /// Test that dividing by zero is an error, not a panic.
+    #[test]
+    fn test_interp_divide_by_zero() {
+        assert!(matches!(interp_code("1 / 0"), Err(InterpError::DivideByZero(_))));
+    }
+
+    /// Test the comparison operators.
+    #[test]
+    fn test_interp_comparisons() {
+        interp_code("1 < 2").unwrap();
+        interp_code("2 > 1").unwrap();
+        interp_code("1 <= 1").unwrap();
+        interp_code("1 >= 1").unwrap();
+        interp_code("1 == 1").unwrap();
+        interp_code("1 != 2").unwrap();
+    }
+
+    /// Test truthiness and the `!` operator.
+    #[test]
+    fn test_interp_bang() {
+        interp_code("!false").unwrap();
+        interp_code("!nil").unwrap();
+    }
+
+    /// Test string concatenation with `+`.
+    #[test]
+    fn test_interp_string_concat() {
+        interp_code("\"foo\" + \"bar\"").unwrap();
+    }
+
+    /// Test that a `while` loop whose condition is false up front never runs its body.
+    #[test]
+    fn test_interp_while_false_never_runs() {
+        use crate::parser;
+
+        let ast = parser::produce_ast("let i = 0; while (i > 10) { i = 99; } i").unwrap();
+        let results = Interpreter::new(ast).parse();
+
+        if let Some(Ok(RuntimeVal::Number(n))) = results.last() {
+            assert_eq!(*n, 0.0);
+        } else {
+            panic!("Expected last statement to evaluate to Number(0.0). Got '{:?}'!", results.last());
+        }
+    }
+
+    /// Test that a block introduces its own scope, shadowing an outer binding.
+    #[test]
+    fn test_interp_block_scope() {
+        use crate::parser;
+
+        let ast = parser::produce_ast("let x = 1; { let x = 2; } x").unwrap();
+        let results = Interpreter::new(ast).parse();
+
+        if let Some(Ok(RuntimeVal::Number(n))) = results.last() {
+            assert_eq!(*n, 1.0);
+        } else {
+            panic!("Expected last statement to evaluate to Number(1.0). Got '{:?}'!", results.last());
+        }
+    }
+
+    /// Test that an identifier resolves to the value its `let` binding was given.
+    #[test]
+    fn test_interp_identifier_resolves_let_binding() {
+        use crate::parser;
+
+        let ast = parser::produce_ast("let x = 5; x").unwrap();
+        let results = Interpreter::new(ast).parse();
+
+        if let Some(Ok(RuntimeVal::Number(n))) = results.last() {
+            assert_eq!(*n, 5.0);
+        } else {
+            panic!("Expected last statement to evaluate to Number(5.0). Got '{:?}'!", results.last());
+        }
+    }
+
+    /// Test that assigning to an outer binding from inside a `while` body's block
+    /// mutates it in place, rather than shadowing it in the block's transient scope.
+    #[test]
+    fn test_interp_while_assignment_mutates_outer_scope() {
+        use crate::parser;
+
+        let ast = parser::produce_ast("let i = 0; while (i < 3) { i = i + 1; } i").unwrap();
+        let results = Interpreter::new(ast).parse();
+
+        if let Some(Ok(RuntimeVal::Number(n))) = results.last() {
+            assert_eq!(*n, 3.0);
+        } else {
+            panic!("Expected last statement to evaluate to Number(3.0). Got '{:?}'!", results.last());
+        }
+    }
+
+    /// Test that assigning to a name with no binding anywhere in scope is an error.
+    #[test]
+    fn test_interp_assign_undefined_variable() {
+        assert!(matches!(interp_code("x = 1"), Err(InterpError::UndefinedVariable(_, _))));
+    }
+
+    /// Test that referencing an undefined variable is an error, not a panic.
+    #[test]
+    fn test_interp_undefined_variable() {
+        assert!(matches!(interp_code("x"), Err(InterpError::UndefinedVariable(_, _))));
+    }
+
+    /// Test that code the lexer can't tokenize is reported as an error,
+    /// not a panic, all the way through `interp_code`.
+    #[test]
+    fn test_interp_code_reports_lex_errors_without_panicking() {
+        assert!(matches!(interp_code("1 @ 2"), Err(InterpError::ParseErrors(_))));
+    }
 }
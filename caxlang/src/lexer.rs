@@ -1,14 +1,18 @@
 // External imports
 use logos::Logos;
+use thiserror::Error;
 
 // Internal imports
 
 use std::num::ParseIntError;
 
-#[derive(Default, Debug, Clone, PartialEq)]
+/// An error encountered while tokenizing source code.
+#[derive(Default, Debug, Clone, PartialEq, Error)]
 pub enum LexingError {
+    #[error("invalid integer literal: {0}")]
     InvalidInteger(String),
     #[default]
+    #[error("unrecognized character")]
     NonAsciiCharacter,
 }
 
@@ -80,15 +84,61 @@ pub enum Token {
     LParen,
     #[regex(r"\)")]
     RParen,
+    #[regex(r"\{")]
+    LBrace,
+    #[regex(r"\}")]
+    RBrace,
+
+    // Keywords
+    #[regex(r"let")]
+    Let,
+    #[regex(r"while")]
+    While,
 
     // Other
     #[regex(r"nil")]
-    Nil
+    Nil,
+    #[regex(r";")]
+    Semicolon
+}
+
+/// A 1-based line/column position within a source string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// Converts a byte offset into `source` to the (line, column) it falls on,
+/// by scanning every newline up to that offset.
+pub fn offset_to_position(source: &str, offset: usize) -> Position {
+    let mut line = 1;
+    let mut column = 1;
+
+    for ch in source[..offset].chars() {
+        if ch == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+
+    Position { line, column }
 }
 
-/// Tokenizes code into a series of tokens.
-pub fn tokenize(code: &'static str) -> impl Iterator<Item = Result<Token, LexingError>> {
+/// Tokenizes code into a series of tokens, each paired with the position
+/// in `code` it was lexed from.
+pub fn tokenize(code: &'static str) -> impl Iterator<Item = (Result<Token, LexingError>, Position)> {
     Token::lexer(code)
+        .spanned()
+        .map(move |(tk, span)| (tk, offset_to_position(code, span.start)))
 }
 
 #[cfg(test)]
@@ -100,13 +150,22 @@ mod tests {
     #[test]
     fn test_tokenize() {
         // Try tokenizing simple string and printing tokens
-        for tk in tokenize(
+        for (tk, pos) in tokenize(
             "123.42 (23.43) * 123.43 sd + \"Hello\""
         ) {
-            println!("{:?}", match tk {
+            println!("{:?} at {}", match tk {
                 Ok(tk) => tk,
                 Err(e) => panic!("Lexer Error: '{:?}'!", e)
-            });
+            }, pos);
         }
     }
+
+    /// Tests that `offset_to_position` accounts for newlines.
+    #[test]
+    fn test_offset_to_position() {
+        let source = "1 + 2\n3 + 4";
+        let pos = offset_to_position(source, 8);
+
+        assert_eq!(pos, Position { line: 2, column: 3 });
+    }
 }
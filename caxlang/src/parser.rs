@@ -1,7 +1,8 @@
 // External imports
+use thiserror::Error;
 
 // Internal imports
-use crate::lexer::Token;
+use crate::lexer::{LexingError, Position, Token};
 
 /// A literal type.
 #[derive(Debug, Clone)]
@@ -18,168 +19,359 @@ pub enum Literal {
 #[derive(Debug, Clone)]
 pub enum Expr {
     Literal(Literal),
+    /// A reference to a variable bound by a `let` statement.
+    Identifier(String, Position),
     Grouping(Box<Expr>),
     Unary {
         op: Token,
-        right: Box<Expr>
+        right: Box<Expr>,
+        position: Position
     },
     Binary {
         left: Box<Expr>,
         op: Token,
-        right: Box<Expr>
+        right: Box<Expr>,
+        position: Position
+    },
+    /// Assigns to an existing binding, walking outward from the innermost scope.
+    /// Unlike `let`, this never introduces a new binding.
+    Assign {
+        name: String,
+        value: Box<Expr>,
+        position: Position
+    }
+}
+
+/// A statement for the parser.
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    Expr(Box<Expr>),
+    Let {
+        name: String,
+        initializer: Option<Box<Expr>>
+    },
+    While {
+        condition: Box<Expr>,
+        body: Box<Stmt>
+    },
+    Block(Vec<Stmt>)
+}
+
+/// An error produced while parsing tokens into an AST.
+#[derive(Debug, Clone, Error)]
+pub enum ParseError {
+    #[error("at {position}: expected {expected}, got '{found:?}'")]
+    UnexpectedToken {
+        found: Token,
+        expected: &'static str,
+        position: Position
+    },
+    #[error("at {position}: expected {expected}, but reached the end of input")]
+    UnexpectedEof {
+        expected: &'static str,
+        position: Position
+    },
+    #[error("at {position}: invalid assignment target")]
+    InvalidAssignmentTarget {
+        position: Position
+    },
+    #[error("at {position}: {source}")]
+    LexError {
+        source: LexingError,
+        position: Position
     }
 }
 
 /// Converts tokens into an AST.
 pub struct Parser {
     tokens: Vec<Token>,
+    positions: Vec<Position>,
+    /// Position to report when the parser runs out of tokens.
+    eof_position: Position,
     pos: usize
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, pos: 0 }
+    pub fn new(tokens: Vec<Token>, positions: Vec<Position>, eof_position: Position) -> Self {
+        Self { tokens, positions, eof_position, pos: 0 }
     }
 
-    /// Parses the tokens into an AST.
-    pub fn parse(&mut self) -> Vec<Box<Expr>> {
-        // Create new AST
+    /// Parses the tokens into an AST, collecting every error encountered
+    /// rather than stopping at the first one.
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<ParseError>> {
+        // Create new AST and error list
         let mut ast = Vec::new();
+        let mut errors = Vec::new();
+
+        while self.peek().is_some() {
+            match self.declaration() {
+                Ok(stmt) => ast.push(stmt),
+                Err(e) => {
+                    errors.push(e);
+                    self.synchronize();
+                }
+            }
+        }
 
-        ast.push(self.expression());
+        if errors.is_empty() {
+            Ok(ast)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Returns a new declaration, i.e. a `let` binding or any other statement.
+    fn declaration(&mut self) -> Result<Stmt, ParseError> {
+        if self.tmatch(vec![Token::Let]) {
+            self.let_declaration()
+        } else {
+            self.statement()
+        }
+    }
 
-        // Return AST
-        ast
+    /// Returns a new `let` declaration, having already consumed the `let` keyword.
+    fn let_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let name = match self.peek().cloned() {
+            Some(Token::Ident(name)) => { self.advance(); name },
+            _ => return Err(self.error("identifier after 'let'"))
+        };
+
+        let initializer = if self.tmatch(vec![Token::Equal]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        // Statement terminator is optional, matching how a lone expression is parsed
+        self.tmatch(vec![Token::Semicolon]);
+
+        Ok(Stmt::Let { name, initializer })
+    }
+
+    /// Returns a new statement.
+    fn statement(&mut self) -> Result<Stmt, ParseError> {
+        if self.tmatch(vec![Token::While]) {
+            return self.while_statement();
+        }
+        if self.tmatch(vec![Token::LBrace]) {
+            return Ok(Stmt::Block(self.block()?));
+        }
+
+        self.expr_statement()
+    }
+
+    /// Returns a new `while` statement, having already consumed the `while` keyword.
+    fn while_statement(&mut self) -> Result<Stmt, ParseError> {
+        self.consume(Token::LParen, "'(' after 'while'")?;
+        let condition = self.expression()?;
+        self.consume(Token::RParen, "')' after while condition")?;
+
+        let body = self.statement()?;
+
+        Ok(Stmt::While { condition, body: Box::new(body) })
+    }
+
+    /// Returns the statements inside a block, having already consumed the opening `{`.
+    fn block(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        let mut stmts = Vec::new();
+
+        while self.peek().is_some() && !self.check(Token::RBrace) {
+            stmts.push(self.declaration()?);
+        }
+
+        self.consume(Token::RBrace, "'}' after block")?;
+
+        Ok(stmts)
+    }
+
+    /// Returns a new expression statement.
+    fn expr_statement(&mut self) -> Result<Stmt, ParseError> {
+        let expr = self.expression()?;
+
+        // Statement terminator is optional, so a bare expression still parses
+        self.tmatch(vec![Token::Semicolon]);
+
+        Ok(Stmt::Expr(expr))
     }
 
     /// Returns a new expression.
-    fn expression(&mut self) -> Box<Expr> {
-        self.equality()
+    fn expression(&mut self) -> Result<Box<Expr>, ParseError> {
+        self.assignment()
+    }
+
+    /// Returns a new assignment expression, e.g. `x = x + 1`.
+    /// Right-associative, and binds looser than every other operator.
+    fn assignment(&mut self) -> Result<Box<Expr>, ParseError> {
+        let expr = self.equality()?;
+
+        if self.tmatch(vec![Token::Equal]) {
+            let position = self.previous_position();
+            // Right-associative: the value itself may be another assignment
+            let value = self.assignment()?;
+
+            return match *expr {
+                Expr::Identifier(name, _) => Ok(Box::new(Expr::Assign { name, value, position })),
+                _ => Err(ParseError::InvalidAssignmentTarget { position })
+            };
+        }
+
+        Ok(expr)
     }
 
     /// Returns a new equality expression.
-    fn equality(&mut self) -> Box<Expr> {
-        let mut expr = self.comparison();
+    fn equality(&mut self) -> Result<Box<Expr>, ParseError> {
+        let mut expr = self.comparison()?;
 
         while self.tmatch(vec![Token::NEqual, Token::DEqual]) {
             // Get operator
             let op = self.previous().clone();
+            let position = self.previous_position();
             // Get current token
-            let right = self.comparison();
+            let right = self.comparison()?;
             // Create binary expression with left, op, and right expressions
-            expr = Box::new(Expr::Binary { left: expr, op, right })
+            expr = Box::new(Expr::Binary { left: expr, op, right, position })
         }
 
-        expr
+        Ok(expr)
     }
 
     /// Returns a new comparison expression.
-    fn comparison(&mut self) -> Box<Expr> {
+    fn comparison(&mut self) -> Result<Box<Expr>, ParseError> {
         // Get current term
-        let mut expr = self.term();
+        let mut expr = self.term()?;
 
         // Add additional expressions, if necessary
         while self.tmatch(vec![Token::Less, Token::Greater, Token::LEqual, Token::GEqual]) {
             let op = self.previous().clone();
-            let right = self.term();
-            expr = Box::new(Expr::Binary { left: expr, op, right });
+            let position = self.previous_position();
+            let right = self.term()?;
+            expr = Box::new(Expr::Binary { left: expr, op, right, position });
         }
 
         // Return new expr
-        expr
+        Ok(expr)
     }
 
     /// Returns a new term expression.
-    fn term(&mut self) -> Box<Expr> {
+    fn term(&mut self) -> Result<Box<Expr>, ParseError> {
         // Get current term
-        let mut expr = self.factor();
+        let mut expr = self.factor()?;
 
         // Add additional expressions, if necessary
         while self.tmatch(vec![Token::Minus, Token::Plus]) {
             let op = self.previous().clone();
-            let right = self.factor();
-            expr = Box::new(Expr::Binary { left: expr, op, right });
+            let position = self.previous_position();
+            let right = self.factor()?;
+            expr = Box::new(Expr::Binary { left: expr, op, right, position });
         }
- 
+
         // Return new expr
-        expr
+        Ok(expr)
     }
 
     /// Returns a new factor expression.
-    fn factor(&mut self) -> Box<Expr> {
+    fn factor(&mut self) -> Result<Box<Expr>, ParseError> {
         // Get current unary
-        let mut expr = self.unary();
+        let mut expr = self.unary()?;
 
         // Add additional expressions, if necessary
         while self.tmatch(vec![Token::Div, Token::Mult]) {
             let op = self.previous().clone();
-            let right = self.unary();
-            expr = Box::new(Expr::Binary { left: expr, op, right });
+            let position = self.previous_position();
+            let right = self.unary()?;
+            expr = Box::new(Expr::Binary { left: expr, op, right, position });
         }
- 
+
         // Return new expr
-        expr
+        Ok(expr)
     }
 
     /// Returns a new unary expression.
-    fn unary(&mut self) -> Box<Expr> {
+    fn unary(&mut self) -> Result<Box<Expr>, ParseError> {
         // Parse '!' or '-', if necessary
         if self.tmatch(vec![Token::Bang, Token::Minus]) {
             let op = self.previous().clone();
-            let right = self.unary();
-            return Box::new(Expr::Unary { op, right });
+            let position = self.previous_position();
+            let right = self.unary()?;
+            return Ok(Box::new(Expr::Unary { op, right, position }));
         }
- 
+
         // Return new primary
         self.primary()
     }
 
     /// Returns a new primary expression.
-    fn primary(&mut self) -> Box<Expr> {
-        if let Token::String(s) = self.peek().unwrap().clone() {
+    fn primary(&mut self) -> Result<Box<Expr>, ParseError> {
+        if let Some(Token::String(s)) = self.peek().cloned() {
             self.advance();
-            return Box::new(Expr::Literal(Literal::String(s)));
+            return Ok(Box::new(Expr::Literal(Literal::String(s))));
         }
-        if let Token::Number(n) = self.peek().unwrap().clone() {
+        if let Some(Token::Number(n)) = self.peek().cloned() {
             self.advance();
-            return Box::new(Expr::Literal(Literal::Number(n)));
+            return Ok(Box::new(Expr::Literal(Literal::Number(n))));
+        }
+        if let Some(Token::Ident(name)) = self.peek().cloned() {
+            let position = self.peek_position();
+            self.advance();
+            return Ok(Box::new(Expr::Identifier(name, position)));
         }
         else if self.tmatch(vec![Token::True]) {
-            return Box::new(Expr::Literal(Literal::True));
+            return Ok(Box::new(Expr::Literal(Literal::True)));
         }
         else if self.tmatch(vec![Token::False]) {
-            return Box::new(Expr::Literal(Literal::False));
+            return Ok(Box::new(Expr::Literal(Literal::False)));
         }
         else if self.tmatch(vec![Token::Nil]) {
-            return Box::new(Expr::Literal(Literal::Nil));
+            return Ok(Box::new(Expr::Literal(Literal::Nil)));
         }
         else if self.tmatch(vec![Token::LParen]) {
-            let expr = self.expression();
-            self.consume(Token::RParen, "Expected ')' after expression!");
-            return Box::new(Expr::Grouping(expr));
+            let expr = self.expression()?;
+            self.consume(Token::RParen, "')' after expression")?;
+            return Ok(Box::new(Expr::Grouping(expr)));
         }
         else {
-            self.panic("Expected expression.");
-            unimplemented!();
+            Err(self.error("expression"))
         }
     }
 
-    /// Panics an detailed error, given a general message.
-    fn panic(&self, msg: &'static str) {
+    /// Builds a `ParseError` for the current token, given a description of
+    /// what was expected instead.
+    fn error(&self, expected: &'static str) -> ParseError {
         match self.peek() {
-            Some(tk) => panic!("[line N]: at '{:?}', {}!", tk, msg),
-            None => panic!("[line N]: {}!", msg)
-        };
+            Some(tk) => ParseError::UnexpectedToken { found: tk.clone(), expected, position: self.peek_position() },
+            None => ParseError::UnexpectedEof { expected, position: self.peek_position() }
+        }
+    }
+
+    /// Advances past tokens until a likely statement boundary is reached,
+    /// so parsing can resume after an error instead of aborting entirely.
+    fn synchronize(&mut self) {
+        self.advance();
+
+        while self.peek().is_some() {
+            // Reached a statement terminator; next token starts a fresh statement
+            if *self.previous() == Token::Semicolon {
+                return;
+            }
+
+            match self.peek() {
+                // Reached what looks like the start of the next statement
+                Some(Token::Let) | Some(Token::While) | Some(Token::LBrace) => return,
+                _ => { self.advance(); }
+            }
+        }
     }
 
-    /// Trys to Consumes a token, panicing if the token does not exist.
-    fn consume(&mut self, token: Token, panic_msg: &'static str) {
-        if !self.tmatch(vec![token]) {
-            panic!("{}", panic_msg);
+    /// Trys to consume a token, returning a `ParseError` if it does not match.
+    fn consume(&mut self, token: Token, expected: &'static str) -> Result<(), ParseError> {
+        if self.tmatch(vec![token]) {
+            Ok(())
+        } else {
+            Err(self.error(expected))
         }
     }
 
-    /// If the current token's value is equal to the one of the provided tokens,  
+    /// If the current token's value is equal to the one of the provided tokens,
     /// advances and returns true. If one of the tokens does not match, returns false.
     fn tmatch(&mut self, tokens: Vec<Token>) -> bool {
         // Iterate over tokens and do stuff if they match current token
@@ -193,25 +385,40 @@ impl Parser {
         return false;
     }
 
-    /// Returns whether the current token matches a provided token value.  
+    /// Returns whether the current token matches a provided token value.
     /// This does NOT consume any tokens, compared to `tmatch()`.
-    /// 
+    ///
     /// ## Panics
-    /// This function panics if self.pos is greater than  
+    /// This function panics if self.pos is greater than
     /// or equal to self.tokens length
     fn check(&self, tk: Token) -> bool {
         self.peek().is_some() && *self.peek().unwrap() == tk
     }
 
     /// Returns the token at position `self.pos - 1`.
-    /// 
+    ///
     /// ## Panics
     /// This function panics if a token at `self.pos - 1` does not exist.
     fn previous(&self) -> &Token {
         self.tokens.get(self.pos - 1).unwrap()
     }
 
-    /// Returns the current token at self.pos.  
+    /// Returns the position of the token at `self.pos - 1`,
+    /// or `eof_position` if there is none.
+    fn previous_position(&self) -> Position {
+        self.pos.checked_sub(1)
+            .and_then(|i| self.positions.get(i))
+            .copied()
+            .unwrap_or(self.eof_position)
+    }
+
+    /// Returns the position of the current token at self.pos,
+    /// or `eof_position` if there is none.
+    fn peek_position(&self) -> Position {
+        self.positions.get(self.pos).copied().unwrap_or(self.eof_position)
+    }
+
+    /// Returns the current token at self.pos.
     /// This does NOT modify the parser in any way.
     fn peek(&self) -> Option<&Token> {
         self.tokens.get(self.pos)
@@ -235,19 +442,43 @@ impl Parser {
 }
 
 /// Returns the ast representation of code.
-pub fn produce_ast(code: &'static str) -> Vec<Box<Expr>> {
+pub fn produce_ast(code: &'static str) -> Result<Vec<Stmt>, Vec<ParseError>> {
     // Create new lexer
     let lexer = crate::lexer::tokenize(code);
-    // Create parser from lexer
-    let mut parser = Parser::new(lexer.map(|i| i.unwrap())
-        .collect());
 
-    // Return parsed ast
-    parser.parse()
+    // Split lexed (token, position) pairs into parallel vecs for the parser,
+    // collecting any unlexable characters as errors instead of unwrapping
+    let mut tokens = Vec::new();
+    let mut positions = Vec::new();
+    let mut errors = Vec::new();
+
+    for (tk, position) in lexer {
+        match tk {
+            Ok(tk) => {
+                tokens.push(tk);
+                positions.push(position);
+            }
+            Err(source) => errors.push(ParseError::LexError { source, position })
+        }
+    }
+
+    let eof_position = crate::lexer::offset_to_position(code, code.len());
+
+    // Create parser from the tokens that did lex successfully
+    let mut parser = Parser::new(tokens, positions, eof_position);
+
+    match parser.parse() {
+        Ok(ast) if errors.is_empty() => Ok(ast),
+        Ok(_) => Err(errors),
+        Err(parse_errors) => {
+            errors.extend(parse_errors);
+            Err(errors)
+        }
+    }
 }
 
 /// Prints ast tree as string.
-pub fn print_ast(ast: &Vec<Box<Expr>>) {
+pub fn print_ast(ast: &Vec<Stmt>) {
     println!("{:?}", ast);
 }
 
@@ -260,22 +491,29 @@ mod tests {
     #[test]
     fn test_parser_simple_math_operations() {
         // Test addition
-        print_ast(&produce_ast("1 + 2"));
+        print_ast(&produce_ast("1 + 2").unwrap());
         // Test subtraction
-        print_ast(&produce_ast("1 - 2"));
+        print_ast(&produce_ast("1 - 2").unwrap());
         // Test multiplication
-        print_ast(&produce_ast("1 * 2"));
+        print_ast(&produce_ast("1 * 2").unwrap());
         // Test division
-        print_ast(&produce_ast("1 / 2"));
+        print_ast(&produce_ast("1 / 2").unwrap());
     }
 
     /// Tests ast production for correct unary value assignment.
     #[test]
     fn test_parser_unary() {
+        let ast = produce_ast("-1 + -2").unwrap();
+
+        // Ensure the single statement is an expression statement
+        let Stmt::Expr(expr) = ast.get(0).unwrap().clone() else {
+            panic!("Expression statement expected. Got '{:?}'!", ast.get(0));
+        };
+
         // Ensure unary
-        if let Expr::Binary {left, ..} = *(*produce_ast("-1 + -2").get(0).unwrap()).clone() {
+        if let Expr::Binary {left, ..} = *expr {
             if let Expr::Unary {..} = (*left).clone() {
-                
+
             } else {
                 panic!("Unary expression expected. Got '{:?}'!", (*left).clone())
             }
@@ -284,16 +522,71 @@ mod tests {
         }
     }
 
+    /// Tests that `let` declarations and identifiers parse.
+    #[test]
+    fn test_parser_let_declaration() {
+        let ast = produce_ast("let x = 5; x").unwrap();
+
+        assert!(matches!(ast.get(0), Some(Stmt::Let { .. })));
+        assert!(matches!(ast.get(1), Some(Stmt::Expr(_))));
+    }
+
+    /// Tests that `while` loops and blocks parse.
+    #[test]
+    fn test_parser_while_and_block() {
+        let ast = produce_ast("while (1 < 2) { let x = 1; }").unwrap();
+
+        assert!(matches!(ast.get(0), Some(Stmt::While { .. })));
+    }
+
     /// Tests ast production for complex math operations.
     #[test]
     fn test_parser_complex_math_operations() {
         // Test addition
-        print_ast(&produce_ast("1 + 2 + 4"));
+        print_ast(&produce_ast("1 + 2 + 4").unwrap());
         // Test subtraction
-        print_ast(&produce_ast("1 - 2 - 5"));
+        print_ast(&produce_ast("1 - 2 - 5").unwrap());
         // Test multiplication
-        print_ast(&produce_ast("1 * 2 * 3"));
+        print_ast(&produce_ast("1 * 2 * 3").unwrap());
         // Test division
-        print_ast(&produce_ast("1 / 2 / 7"));
+        print_ast(&produce_ast("1 / 2 / 7").unwrap());
+    }
+
+    /// Tests that assigning to an identifier parses into an `Expr::Assign`.
+    #[test]
+    fn test_parser_assignment() {
+        let ast = produce_ast("x = 1").unwrap();
+
+        let Stmt::Expr(expr) = ast.get(0).unwrap().clone() else {
+            panic!("Expression statement expected. Got '{:?}'!", ast.get(0));
+        };
+
+        assert!(matches!(*expr, Expr::Assign { .. }));
+    }
+
+    /// Tests that assigning to anything other than an identifier is a parse error.
+    #[test]
+    fn test_parser_invalid_assignment_target() {
+        let errors = produce_ast("1 = 2").unwrap_err();
+
+        assert!(matches!(errors.as_slice(), [ParseError::InvalidAssignmentTarget { .. }]));
+    }
+
+    /// Tests that a syntax error is reported instead of panicking,
+    /// as exactly one error for a single incomplete expression.
+    #[test]
+    fn test_parser_reports_errors_without_panicking() {
+        let errors = produce_ast("1 +").unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+    }
+
+    /// Tests that a character the lexer can't tokenize is reported as a
+    /// `ParseError::LexError` instead of panicking inside `produce_ast`.
+    #[test]
+    fn test_parser_reports_lex_errors_without_panicking() {
+        let errors = produce_ast("1 @ 2").unwrap_err();
+
+        assert!(matches!(errors.as_slice(), [ParseError::LexError { .. }]));
     }
 }
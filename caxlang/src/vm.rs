@@ -5,6 +5,9 @@
 // External imports
 use caxlang_proc::Display;
 
+// Internal imports
+use crate::interpreter::RuntimeVal;
+
 /// Basically the match expression, but for opcodes on bytes.
 macro_rules! opcode_match {
     ($byte:expr, $( $opcode:expr => $code:block ),*) => {
@@ -29,13 +32,16 @@ macro_rules! operand_match {
 #[derive(Debug)]
 pub enum OpCode {
     Add = 0,
-    Sub
+    Sub,
+    /// Loads a constant from the compiler's constant pool into a register.
+    /// Operands are `[dst_register, constant_index]`.
+    LoadConst
 }
 
 /// An register for a VM.
-/// 
+///
 /// Bytecode example: `MOV [register] [operand_one]`
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Register {
     R0 = 0,
     R1,
@@ -54,7 +60,7 @@ impl Into<Byte> for Register {
 }
 
 /// A more readable alias for a u8.
-type Byte = u8;
+pub(crate) type Byte = u8;
 
 impl Into<Byte> for OpCode {
     fn into(self) -> Byte {
@@ -62,6 +68,17 @@ impl Into<Byte> for OpCode {
     }
 }
 
+/// The kind of value encoded in an instruction's operand byte.
+#[derive(Debug, Clone, Copy)]
+enum OperandKind {
+    /// This operand slot isn't used by the opcode.
+    None,
+    /// Operand is a register.
+    Register,
+    /// Operand is an index into the constant pool.
+    Constant
+}
+
 /// A chunk that the VM can read.
 #[derive(Display)]
 pub struct Chunk {
@@ -94,25 +111,75 @@ impl Chunk {
         ""
     }
 
-    /// Returns a representation  
+    /// Returns a representation
     /// of this chunk as plain text.
+    ///
+    /// Shares `mnemonic_and_kinds` with `disassemble_at` so every opcode only
+    /// has to be described in one place; unlike `disassemble_at`, this has no
+    /// constant pool to resolve against, so a `Constant` operand is rendered
+    /// as its bare pool index rather than its value.
     pub fn dissasemble(&self) -> String {
-        // Create variables to hold string repr of chunk
-        let mut opcode_str: &'static str = "";
-        let operand_one: &'static str;
-        let operand_two: &'static str;
+        let Some((mnemonic, kinds)) = self.mnemonic_and_kinds() else {
+            return format!("<unknown opcode '{}'>", self.bytes[0]);
+        };
 
-        // Get string version of operand one and two.
-        operand_one = self.parse_operand(0);
-        operand_two = self.parse_operand(1);
+        let operand_one = self.render_bare_operand(0, kinds[0]);
+        let operand_two = self.render_bare_operand(1, kinds[1]);
+
+        format!("{} {} {}", mnemonic, operand_one, operand_two)
+    }
+
+    /// Renders a single operand byte according to its kind, without a constant
+    /// pool to resolve a `Constant` operand's value against.
+    fn render_bare_operand(&self, index: usize, kind: OperandKind) -> String {
+        match kind {
+            OperandKind::None => String::new(),
+            OperandKind::Register => self.parse_operand(index).to_string(),
+            OperandKind::Constant => format!("C{}", self.bytes[index + 1])
+        }
+    }
+
+    /// Returns this chunk's mnemonic and the kind of value each of its operand bytes holds,
+    /// or `None` if the opcode byte isn't recognized.
+    fn mnemonic_and_kinds(&self) -> Option<(&'static str, [OperandKind; 2])> {
+        let mut result = None;
 
         opcode_match!{self.bytes[0],
-            OpCode::Add => { opcode_str = "Add"; },
-            OpCode::Sub => { opcode_str = "Sub"; }
+            OpCode::Add => { result = Some(("Add", [OperandKind::Register, OperandKind::Register])); },
+            OpCode::Sub => { result = Some(("Sub", [OperandKind::Register, OperandKind::Register])); },
+            OpCode::LoadConst => { result = Some(("LoadConst", [OperandKind::Register, OperandKind::Constant])); }
+        }
+
+        result
+    }
+
+    /// Renders a single operand byte according to its kind, resolving constant
+    /// indices against `constants`.
+    fn render_operand(&self, index: usize, kind: OperandKind, constants: &[f64]) -> String {
+        match kind {
+            OperandKind::None => String::new(),
+            OperandKind::Register => self.parse_operand(index).to_string(),
+            OperandKind::Constant => {
+                let idx = self.bytes[index + 1];
+                match constants.get(idx as usize) {
+                    Some(value) => format!("{} ; {}", idx, value),
+                    None => format!("{} ; <out of range>", idx)
+                }
+            }
         }
+    }
+
+    /// Renders this chunk as a single, column-aligned disassembly row at byte `offset`,
+    /// resolving register operands to their names and constant operands to their pool values.
+    pub fn disassemble_at(&self, offset: usize, constants: &[f64]) -> String {
+        let Some((mnemonic, kinds)) = self.mnemonic_and_kinds() else {
+            return format!("{:04} {:<10} <unknown opcode '{}'>", offset, "???", self.bytes[0]);
+        };
+
+        let operand_one = self.render_operand(0, kinds[0], constants);
+        let operand_two = self.render_operand(1, kinds[1], constants);
 
-        // Return string repr of current chunk
-        format!("{} {} {}", opcode_str, operand_one, operand_two).to_string()
+        format!("{:04} {:<10} {:<8} {}", offset, mnemonic, operand_one, operand_two)
     }
 }
 
@@ -122,15 +189,97 @@ impl std::fmt::Debug for Chunk {
     }
 }
 
+/// An error that can be returned from the VM.
+#[derive(Debug, thiserror::Error)]
+pub enum VmError {
+    #[error("unknown opcode byte '{0}'")]
+    UnknownOpcode(Byte),
+    #[error("register byte '{0}' is out of range")]
+    RegisterOutOfRange(Byte),
+    #[error("constant index '{0}' is out of range")]
+    ConstantIndexOutOfRange(Byte)
+}
+
+/// Decodes a register operand byte, erroring instead of panicking on a bad byte.
+fn decode_register(byte: Byte) -> Result<Register, VmError> {
+    operand_match!{byte,
+        Register::R0 => { return Ok(Register::R0) },
+        Register::R1 => { return Ok(Register::R1) },
+        Register::R2 => { return Ok(Register::R2) },
+        Register::R3 => { return Ok(Register::R3) },
+        Register::R4 => { return Ok(Register::R4) },
+        Register::R5 => { return Ok(Register::R5) },
+        Register::R6 => { return Ok(Register::R6) },
+        Register::R7 => { return Ok(Register::R7) }
+    };
+
+    Err(VmError::RegisterOutOfRange(byte))
+}
+
 /// The VM that performs actions based on given bytecode chunks.
 pub struct VM {
-    chunks: Vec<Chunk>
+    chunks: Vec<Chunk>,
+    /// The constant pool emitted by the compiler, read by `OpCode::LoadConst`.
+    constants: Vec<f64>
 }
 
 impl VM {
-    /// Returns a new VM given a list of chunks.
-    pub fn new(chunks: Vec<Chunk>) -> Self {
-        Self { chunks }
+    /// Returns a new VM given a list of chunks and the constants they reference.
+    pub fn new(chunks: Vec<Chunk>, constants: Vec<f64>) -> Self {
+        Self { chunks, constants }
+    }
+
+    /// Runs every chunk in sequence against an 8-register file,
+    /// returning the value left in `R0` once the program finishes.
+    pub fn run(&mut self) -> Result<RuntimeVal, VmError> {
+        // The VM's register file.
+        let mut regs = [0.0_f64; 8];
+
+        for chunk in &self.chunks {
+            let mut matched = false;
+
+            opcode_match!{chunk.bytes[0],
+                OpCode::Add => {
+                    let a = decode_register(chunk.bytes[1])?;
+                    let b = decode_register(chunk.bytes[2])?;
+                    regs[a as usize] += regs[b as usize];
+                    matched = true;
+                },
+                OpCode::Sub => {
+                    let a = decode_register(chunk.bytes[1])?;
+                    let b = decode_register(chunk.bytes[2])?;
+                    regs[a as usize] -= regs[b as usize];
+                    matched = true;
+                },
+                OpCode::LoadConst => {
+                    let dst = decode_register(chunk.bytes[1])?;
+                    let idx = chunk.bytes[2];
+                    regs[dst as usize] = *self.constants.get(idx as usize)
+                        .ok_or(VmError::ConstantIndexOutOfRange(idx))?;
+                    matched = true;
+                }
+            }
+
+            if !matched {
+                return Err(VmError::UnknownOpcode(chunk.bytes[0]));
+            }
+        }
+
+        Ok(RuntimeVal::Number(regs[Register::R0 as usize]))
+    }
+
+    /// Returns a readable disassembly of every chunk in this program, one row per
+    /// chunk with a running byte offset, suitable for printing as a listing.
+    pub fn disassemble(&self) -> String {
+        let mut listing = String::from("OFFSET MNEMONIC   OPERANDS\n");
+
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            let offset = i * chunk.bytes.len();
+            listing.push_str(&chunk.disassemble_at(offset, &self.constants));
+            listing.push('\n');
+        }
+
+        listing
     }
 }
 
@@ -152,4 +301,48 @@ mod tests {
 
         println!("{}", &chunk);
     }
+
+    /// Test that `dissasemble()` recognizes `LoadConst` and renders its second
+    /// operand as a constant-pool index, not a register name.
+    #[test]
+    fn test_chunk_dissasemble_load_const() {
+        let chunk = Chunk::new([OpCode::LoadConst.into(), Register::R0.into(), 3]);
+
+        let line = chunk.dissasemble();
+
+        assert!(line.contains("LoadConst"));
+        assert!(line.contains("R0"));
+        assert!(line.contains("C3"));
+    }
+
+    /// Test that `disassemble_at` resolves a `LoadConst` operand to its constant-pool value.
+    #[test]
+    fn test_chunk_disassemble_at_resolves_constant() {
+        let constants = vec![3.0];
+        let chunk = Chunk::new([OpCode::LoadConst.into(), Register::R0.into(), 0]);
+
+        let line = chunk.disassemble_at(0, &constants);
+
+        assert!(line.contains("LoadConst"));
+        assert!(line.contains("R0"));
+        assert!(line.contains('3'));
+    }
+
+    /// Test that `VM::disassemble` produces one row per chunk with a running offset.
+    #[test]
+    fn test_vm_disassemble_listing() {
+        let constants = vec![1.0, 2.0];
+        let chunks = vec![
+            Chunk::new([OpCode::LoadConst.into(), Register::R0.into(), 0]),
+            Chunk::new([OpCode::LoadConst.into(), Register::R1.into(), 1]),
+            Chunk::new([OpCode::Add.into(), Register::R0.into(), Register::R1.into()])
+        ];
+
+        let vm = VM::new(chunks, constants);
+        let listing = vm.disassemble();
+
+        assert_eq!(listing.lines().count(), 4);
+        assert!(listing.contains("0003"));
+        assert!(listing.contains("0006"));
+    }
 }